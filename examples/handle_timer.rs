@@ -0,0 +1,28 @@
+//! Shows the `TimerHandle`/`Scope` alternative to `my_timer.rs`'s `lazy_static!` global: a timer
+//! owned by whatever needs one (here, per simulated "request") instead of reached via a static.
+
+use spyglass::TimerHandle;
+
+use std::time::Duration;
+
+fn main() {
+    // Two independent timelines, e.g. for two requests handled concurrently; neither sees the
+    // other's timings.
+    let request_a = TimerHandle::new();
+    let request_b = TimerHandle::new();
+
+    handle_request(&request_a, "a");
+    handle_request(&request_b, "b");
+
+    for (label, handle) in [("request_a", &request_a), ("request_b", &request_b)] {
+        println!("{label}:");
+        for (name, stats) in handle.report() {
+            println!("  {name}: {} samples, mean {:?}", stats.count, stats.mean);
+        }
+    }
+}
+
+fn handle_request(timer: &TimerHandle, id: &str) {
+    let _scope = timer.scope(format!("handle_request({id})"));
+    std::thread::sleep(Duration::from_millis(1));
+}
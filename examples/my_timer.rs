@@ -1,6 +1,6 @@
-use spyglass::{func, t, Timer, Timing};
+use spyglass::{func, t, Span, Timer, Timing};
 
-use std::time::{Duration, Instant};
+use std::time::Duration;
 
 fn main() {
     {
@@ -15,22 +15,13 @@ fn main() {
         let _t = MyTimer::new(t!());
     }
 
-    // wait for all the drop threads to finish
-    std::thread::sleep(Duration::from_micros(1));
-
-    match GLOBAL_TIMER.lock() {
-        Ok(queue) => {
-            for timing in queue.iter() {
-                println!(
-                    "{} took {}s ({}ns)",
-                    timing.name,
-                    timing.duration.as_secs(),
-                    timing.duration.as_nanos()
-                );
-            }
-        }
-
-        Err(e) => eprintln!("couldn't check timings: {}", e),
+    for timing in GLOBAL_TIMER.drain() {
+        println!(
+            "{} took {}s ({}ns)",
+            timing.name,
+            timing.duration.as_secs(),
+            timing.duration.as_nanos()
+        );
     }
 }
 
@@ -57,32 +48,28 @@ mod x {
 
 #[derive(Debug)]
 pub struct MyTimer {
-    name: String,
-    begin: Instant,
+    name: Option<String>,
+    span: Option<Span>,
 }
 
 impl MyTimer {
     #[must_use]
     pub fn new<T: ToString>(name: T) -> Self {
         MyTimer {
-            name: name.to_string(),
-            begin: Instant::now(),
+            name: Some(name.to_string()),
+            span: Some(Span::begin()),
         }
     }
 
     fn end(&mut self) -> Timing {
-        Timing {
-            name: self.name.clone(),
-            begin: self.begin,
-            duration: Instant::now() - self.begin,
-        }
+        self.span.take().unwrap().end(self.name.take().unwrap())
     }
 }
 
 impl Drop for MyTimer {
     fn drop(&mut self) {
         let end = self.end();
-        std::thread::spawn(move || GLOBAL_TIMER.queue(end));
+        GLOBAL_TIMER.queue(end);
     }
 }
 
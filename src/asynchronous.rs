@@ -0,0 +1,141 @@
+//! Async-aware timing, for spans that cross `.await` points.
+//!
+//! A synchronous guard like the `MyTimer` from the crate docs measures wall-clock time from
+//! construction to [`Drop`], which is meaningful because a synchronous scope runs start-to-finish
+//! without being parked. A future can be parked for an arbitrary amount of time between polls, so
+//! holding a guard across an `.await` would measure idle time instead of work. [`timed`] and
+//! [`with_timeout`] measure the future itself instead of a scope around it, and queue directly
+//! into a [`Timer`] with no thread spawned per timing ([`with_timeout`]'s deadlines share one
+//! lazily-started background thread).
+
+use crate::deadline;
+use crate::{Outcome, Span, Timer};
+
+use std::future::Future;
+use std::pin::Pin;
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::Arc;
+use std::task::{Context, Poll};
+use std::time::{Duration, Instant};
+
+/// Wraps `fut` so that, once it resolves, the time from creation to completion is queued into
+/// `timer` under `name` as an [`Outcome::Completed`] [`Timing`](crate::Timing).
+pub fn timed<'t, F: Future>(timer: &'t Timer, name: impl Into<String>, fut: F) -> Timed<'t, F> {
+    Timed {
+        timer,
+        name: name.into(),
+        span: Some(Span::detached()),
+        fut,
+    }
+}
+
+/// A future returned by [`timed`]. See its documentation.
+pub struct Timed<'t, F> {
+    timer: &'t Timer,
+    name: String,
+    span: Option<Span>,
+    fut: F,
+}
+
+impl<'t, F: Future> Future for Timed<'t, F> {
+    type Output = F::Output;
+
+    fn poll(self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<Self::Output> {
+        // SAFETY: `fut` is the only structurally-pinned field and is never moved out of; the
+        // other fields are plain Unpin data that we only ever mutate in place.
+        let this = unsafe { self.get_unchecked_mut() };
+        let fut = unsafe { Pin::new_unchecked(&mut this.fut) };
+
+        match fut.poll(cx) {
+            Poll::Ready(output) => {
+                let name = std::mem::take(&mut this.name);
+                this.timer.queue(this.span.take().unwrap().end(name));
+                Poll::Ready(output)
+            }
+            Poll::Pending => Poll::Pending,
+        }
+    }
+}
+
+/// Races `fut` against `dur`. If `fut` resolves first, queues an [`Outcome::Completed`] timing
+/// and resolves to `Some(output)`; if the deadline elapses first, queues an
+/// [`Outcome::TimedOut`] timing instead and resolves to `None`. Mirrors the usual `with_timeout`/`TimeoutError` shape: `select` between the future
+/// and a delay, and report which one resolved.
+pub fn with_timeout<'t, F: Future>(
+    timer: &'t Timer,
+    dur: Duration,
+    name: impl Into<String>,
+    fut: F,
+) -> WithTimeout<'t, F> {
+    WithTimeout {
+        timer,
+        name: name.into(),
+        span: Some(Span::detached()),
+        deadline: Instant::now() + dur,
+        registration: None,
+        expired: Arc::new(AtomicBool::new(false)),
+        fut,
+    }
+}
+
+/// A future returned by [`with_timeout`]. See its documentation.
+pub struct WithTimeout<'t, F> {
+    timer: &'t Timer,
+    name: String,
+    span: Option<Span>,
+    deadline: Instant,
+    /// Id of this call's entry with the shared [`deadline`] scheduler, once registered.
+    registration: Option<u64>,
+    expired: Arc<AtomicBool>,
+    fut: F,
+}
+
+impl<'t, F: Future> Future for WithTimeout<'t, F> {
+    type Output = Option<F::Output>;
+
+    fn poll(self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<Self::Output> {
+        // SAFETY: same reasoning as Timed::poll above — `fut` is never moved out of.
+        let this = unsafe { self.get_unchecked_mut() };
+
+        if this.registration.is_none() {
+            // Registering once with the first-seen waker (rather than re-registering on every
+            // poll) keeps this to a single shared background thread instead of one sleeper per
+            // call; it assumes the executor doesn't change which waker drives this task, which
+            // holds for the runtimes this crate targets.
+            let id = deadline::register(this.deadline, cx.waker().clone(), Arc::clone(&this.expired));
+            this.registration = Some(id);
+        }
+
+        let fut = unsafe { Pin::new_unchecked(&mut this.fut) };
+        if let Poll::Ready(output) = fut.poll(cx) {
+            if let Some(id) = this.registration.take() {
+                deadline::cancel(id);
+            }
+            let name = std::mem::take(&mut this.name);
+            this.timer.queue(this.span.take().unwrap().end(name));
+            return Poll::Ready(Some(output));
+        }
+
+        if this.expired.load(Ordering::Acquire) {
+            // the deadline already fired and removed itself from the scheduler.
+            this.registration = None;
+            let name = std::mem::take(&mut this.name);
+            let mut timing = this.span.take().unwrap().end(name);
+            timing.outcome = Outcome::TimedOut;
+            this.timer.queue(timing);
+            return Poll::Ready(None);
+        }
+
+        Poll::Pending
+    }
+}
+
+impl<'t, F> Drop for WithTimeout<'t, F> {
+    fn drop(&mut self) {
+        // if this future is abandoned before resolving, deregister its deadline instead of
+        // leaving it to fire into a waker nobody's listening to anymore.
+        if let Some(id) = self.registration.take() {
+            deadline::cancel(id);
+        }
+    }
+}
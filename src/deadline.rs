@@ -0,0 +1,91 @@
+//! A single shared background thread that wakes tasks once their deadlines elapse.
+//!
+//! [`with_timeout`](crate::with_timeout) needs *something* to notice when a deadline has passed
+//! and wake the waiting task, but there's no reactor to register a timer with here. Spawning a
+//! thread per call to sleep out the deadline would just reintroduce the "thread per timing"
+//! problem this crate otherwise avoids, except worse: that thread sleeps for the *entire*
+//! timeout, for every in-flight call, for as long as it's in flight. Instead, [`register`] hands
+//! a deadline to one lazily-started background thread shared by the whole process, which just
+//! sleeps until the next deadline (not a fixed interval) and wakes whichever tasks are due.
+
+use std::sync::atomic::{AtomicBool, AtomicU64, Ordering};
+use std::sync::{Arc, Condvar, Mutex, OnceLock};
+use std::task::Waker;
+use std::time::Instant;
+
+struct Entry {
+    id: u64,
+    deadline: Instant,
+    waker: Waker,
+    expired: Arc<AtomicBool>,
+}
+
+struct Scheduler {
+    entries: Mutex<Vec<Entry>>,
+    woken: Condvar,
+}
+
+static SCHEDULER: OnceLock<Arc<Scheduler>> = OnceLock::new();
+static NEXT_ID: AtomicU64 = AtomicU64::new(0);
+
+fn scheduler() -> &'static Arc<Scheduler> {
+    SCHEDULER.get_or_init(|| {
+        let scheduler = Arc::new(Scheduler {
+            entries: Mutex::new(Vec::new()),
+            woken: Condvar::new(),
+        });
+        let background = Arc::clone(&scheduler);
+        std::thread::spawn(move || run(&background));
+        scheduler
+    })
+}
+
+fn run(scheduler: &Scheduler) {
+    let mut entries = scheduler.entries.lock().unwrap();
+    loop {
+        let now = Instant::now();
+        entries.retain(|entry| {
+            if entry.deadline > now {
+                return true;
+            }
+            entry.expired.store(true, Ordering::Release);
+            entry.waker.wake_by_ref();
+            false
+        });
+
+        entries = match entries.iter().map(|entry| entry.deadline).min() {
+            Some(next) => {
+                scheduler
+                    .woken
+                    .wait_timeout(entries, next.saturating_duration_since(Instant::now()))
+                    .unwrap()
+                    .0
+            }
+            // nothing registered: sleep until register() notifies us of a new deadline.
+            None => scheduler.woken.wait(entries).unwrap(),
+        };
+    }
+}
+
+/// Registers `waker` to be woken, and `expired` to be set, once `deadline` passes. Returns an id
+/// that can be passed to [`cancel`] to deregister it early.
+pub fn register(deadline: Instant, waker: Waker, expired: Arc<AtomicBool>) -> u64 {
+    let scheduler = scheduler();
+    let id = NEXT_ID.fetch_add(1, Ordering::Relaxed);
+    scheduler.entries.lock().unwrap().push(Entry {
+        id,
+        deadline,
+        waker,
+        expired,
+    });
+    // the background thread may currently be sleeping past this (earlier) deadline.
+    scheduler.woken.notify_one();
+    id
+}
+
+/// Deregisters a deadline registered with [`register`], if it hasn't already fired.
+pub fn cancel(id: u64) {
+    if let Some(scheduler) = SCHEDULER.get() {
+        scheduler.entries.lock().unwrap().retain(|entry| entry.id != id);
+    }
+}
@@ -0,0 +1,84 @@
+//! An explicit alternative to the mandatory `lazy_static!` global [`Timer`].
+//!
+//! The doc example on the crate root works, but it forces every consumer to declare their own
+//! global timer and wire up their own `Drop` impl against it, which means a library can't collect
+//! timings into a caller-supplied sink, and two subsystems can't keep separate timelines.
+//! [`TimerHandle`] is a cheap `Arc<Timer>` clone that a [`Scope`] captures at construction time,
+//! so a timer per request, per test, or per actor is a first-class option instead of requiring
+//! global mutable state. [`install_global`] keeps the old single-global behavior available for
+//! callers who still want it.
+
+use crate::{Span, Timer};
+
+use std::ops::Deref;
+use std::sync::{Arc, OnceLock};
+
+/// A cheaply-cloneable handle to a [`Timer`].
+///
+/// Clone it into whatever owns a request, test, or actor, and hand out [`Scope`]s from it; every
+/// clone shares the same underlying queue. Derefs to the underlying [`Timer`], so `drain`,
+/// `report`, and `tree` are all available directly on a handle.
+#[derive(Clone)]
+pub struct TimerHandle(Arc<Timer>);
+
+impl TimerHandle {
+    /// Creates a new, independent timer behind a handle.
+    #[must_use]
+    pub fn new() -> Self {
+        TimerHandle(Arc::new(Timer::new()))
+    }
+
+    /// Opens a [`Scope`] that queues a [`Timing`](crate::Timing) into this handle when dropped.
+    ///
+    /// This is the handle-based equivalent of hand-writing a `MyTimer`: construct one at the top
+    /// of a block and let it go out of scope.
+    #[must_use]
+    pub fn scope(&self, name: impl Into<String>) -> Scope {
+        Scope {
+            handle: self.clone(),
+            name: Some(name.into()),
+            span: Some(Span::begin()),
+        }
+    }
+}
+
+impl Default for TimerHandle {
+    fn default() -> Self {
+        TimerHandle::new()
+    }
+}
+
+impl Deref for TimerHandle {
+    type Target = Timer;
+
+    fn deref(&self) -> &Timer {
+        &self.0
+    }
+}
+
+/// A scope guard obtained from [`TimerHandle::scope`].
+///
+/// Queues its timing into the handle it was created from when dropped, the same way a
+/// hand-written `MyTimer` queues into its global timer.
+pub struct Scope {
+    handle: TimerHandle,
+    name: Option<String>,
+    span: Option<Span>,
+}
+
+impl Drop for Scope {
+    fn drop(&mut self) {
+        let timing = self.span.take().unwrap().end(self.name.take().unwrap());
+        self.handle.queue(timing);
+    }
+}
+
+static GLOBAL: OnceLock<TimerHandle> = OnceLock::new();
+
+/// Installs, if one isn't already installed, a process-wide [`TimerHandle`] and returns it.
+///
+/// This is the old "one big global timer" behavior, kept around for callers who don't need
+/// per-instance timers. Calling it again just returns the handle installed by the first call.
+pub fn install_global() -> TimerHandle {
+    GLOBAL.get_or_init(TimerHandle::new).clone()
+}
@@ -4,17 +4,24 @@
 //! provide your own timer type that implements the Drop trait and adds it to a lazy static global
 //! timer.
 //!
+//! This pattern still works, and [`install_global`] will set up the global timer for you if you'd
+//! rather not declare your own `lazy_static!`. But a global timer isn't required: [`TimerHandle`]
+//! and [`Scope`] give you the same thing (a `Drop`-based scope guard that queues a `Timing`) as an
+//! explicit, cloneable handle instead, so a library can collect timings into a caller-supplied
+//! sink, and two subsystems can keep separate timelines. See `examples/handle_timer.rs`.
+//!
 //! Example usage:
 //!
 //! ```
 //! # fn main() {}
 //! # use inputs::*;
-//! use std::time::Instant;
 //!
 //! #[derive(Debug)]
 //! pub struct MyTimer {
 //!     name: Option<String>,
-//!     begin: Instant,
+//!     // Span replaces a raw Instant: it also records this timer's place on the thread-local
+//!     // stack of currently-open spans, so Timer::tree() can reconstruct parent/child nesting.
+//!     span: Option<Span>,
 //! }
 //!
 //! impl MyTimer {
@@ -22,18 +29,14 @@
 //!     pub fn new(name: String) -> Self {
 //!         MyTimer {
 //!             name: Some(name),
-//!             begin: Instant::now(),
+//!             span: Some(Span::begin()),
 //!         }
 //!     }
 //!
 //!     fn end(&mut self) -> Timing {
-//!         // MyTimer uses an Option to avoid memory copying overhead. Since
+//!         // MyTimer uses Options to avoid memory copying overhead. Since
 //!         // mem::size_of<String>() is 24, Option::take is just a pointer swap.
-//!         Timing {
-//!             name: self.name.take().unwrap(),
-//!             begin: self.begin,
-//!             duration: Instant::now() - self.begin,
-//!         }
+//!         self.span.take().unwrap().end(self.name.take().unwrap())
 //!     }
 //! }
 //!
@@ -41,10 +44,8 @@
 //!     fn drop(&mut self) {
 //!         let end = self.end();
 //!
-//!         // It's not strictly necessary to spawn a separate thread in order to add the timing to
-//!         // the queue, but Drop could block if a lot of MyTimers go out of scope at the same
-//!         // time.
-//!         std::thread::spawn(move || GLOBAL_TIMER.queue(end));
+//!         // queue() never blocks, so there's no need to spawn a thread to avoid blocking Drop.
+//!         GLOBAL_TIMER.queue(end);
 //!     }
 //! }
 //!
@@ -53,15 +54,103 @@
 //! }
 //! ```
 
-use std::sync::{Mutex, MutexGuard, PoisonError};
+use std::collections::BTreeMap;
+use std::ptr;
+use std::sync::atomic::{AtomicPtr, Ordering};
 use std::time::{Duration, Instant};
 
+mod deadline;
+
+mod asynchronous;
+pub use asynchronous::{timed, with_timeout, Timed, WithTimeout};
+
+mod tree;
+pub use tree::{Span, SpanNode};
+
+mod handle;
+pub use handle::{install_global, Scope, TimerHandle};
+
 /// Represents a duration of time.
 #[derive(Debug)]
 pub struct Timing {
     pub name: String,
     pub begin: Instant,
     pub duration: Duration,
+    /// Whether this timing ran to completion or was produced by [`with_timeout`] hitting its
+    /// deadline. Always [`Outcome::Completed`] for timings from a synchronous guard.
+    pub outcome: Outcome,
+    /// Uniquely identifies this span, for reassembling a call tree in [`Timer::tree`].
+    pub id: u64,
+    /// The `id` of whichever span was open on this thread when this one began, if any.
+    pub parent_id: Option<u64>,
+    /// How many spans were already open on this thread when this one began.
+    pub depth: usize,
+}
+
+/// Whether a [`Timing`] completed normally or was cut short by a deadline.
+///
+/// See [`with_timeout`].
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Outcome {
+    /// The timed operation finished on its own.
+    Completed,
+    /// The timed operation was still running when its deadline elapsed.
+    TimedOut,
+}
+
+/// Aggregated statistics for every [`Timing`] recorded under a single name.
+///
+/// Built up incrementally by [`Timer::report`] via [`Stats::update`], so the whole queue only
+/// needs to be walked once no matter how many distinct names it contains.
+#[derive(Debug, Clone, Copy)]
+pub struct Stats {
+    pub count: u64,
+    pub total: Duration,
+    pub min: Duration,
+    pub max: Duration,
+    pub mean: Duration,
+    /// Sum of squares of differences from the mean, in seconds², per Welford's online algorithm.
+    m2: f64,
+}
+
+impl Stats {
+    fn new(first: Duration) -> Self {
+        Stats {
+            count: 1,
+            total: first,
+            min: first,
+            max: first,
+            mean: first,
+            m2: 0.0,
+        }
+    }
+
+    fn update(&mut self, sample: Duration) {
+        self.count += 1;
+        self.total += sample;
+        self.min = self.min.min(sample);
+        self.max = self.max.max(sample);
+
+        // Welford's recurrence, computed in f64 seconds. m2 stays in f64 between updates; the
+        // running mean is stored back as a Duration, so it's rounded to the nanosecond each time.
+        let x = sample.as_secs_f64();
+        let mean_before = self.mean.as_secs_f64();
+        let mean_after = mean_before + (x - mean_before) / self.count as f64;
+        self.m2 += (x - mean_before) * (x - mean_after);
+        self.mean = Duration::from_secs_f64(mean_after.max(0.0));
+    }
+
+    /// The sample standard deviation (Bessel's correction, i.e. divided by `count - 1`), in
+    /// seconds.
+    ///
+    /// Returns `0.0` for a single sample, since variance is undefined for `n < 2`.
+    pub fn stddev(&self) -> f64 {
+        if self.count < 2 {
+            0.0
+        } else {
+            (self.m2 / (self.count - 1) as f64).sqrt()
+        }
+    }
 }
 
 /// Get the name of the current function.
@@ -126,41 +215,174 @@ macro_rules! t {
     };
 }
 
+/// A single entry in the [`Timer`]'s intrusive, lock-free stack.
+struct Node {
+    timing: Timing,
+    next: *mut Node,
+}
+
 /// A timer.
 ///
-/// A timer is essentially just a wrapper around a Mutex. It provides some helper methods to add
-/// timers to it asynchronously.
+/// A timer is a lock-free, multi-producer stack of [`Timing`]s: pushing onto it (`queue`) is a
+/// single lock-free compare-exchange loop, so it's cheap enough to call directly from a `Drop`
+/// impl without spawning a thread. There's no mutex to poison, so `queue` and `drain` are
+/// infallible.
 pub struct Timer {
-    queue: Mutex<Vec<Timing>>,
+    head: AtomicPtr<Node>,
 }
 
 impl Timer {
     /// Creates a new timer.
     pub fn new() -> Self {
         Timer {
-            queue: Mutex::new(Vec::new()),
+            head: AtomicPtr::new(ptr::null_mut()),
         }
     }
 
     /// Adds a timing to the queue.
     ///
-    /// The most typical usage is implementing Drop for some type, constructing a Timing, and then
-    /// calling this method. It's important to note that this method will block until the Timer can
-    /// acquire its mutex, so you may wish to call this method from another thread.
+    /// This is lock-free: it never blocks (a push that loses a race just retries its
+    /// compare-exchange), so it's safe to call directly from a `Drop` impl, even when many timers
+    /// are being dropped from different threads at once.
     pub fn queue(&self, timing: Timing) {
-        match self.lock() {
-            Ok(mut queue) => queue.push(timing),
-            Err(e) => eprintln!("couldn't queue \"{}\": {}", timing.name, e),
+        let node = Box::into_raw(Box::new(Node {
+            timing,
+            next: ptr::null_mut(),
+        }));
+
+        let mut head = self.head.load(Ordering::Relaxed);
+        loop {
+            // SAFETY: node was just allocated above and isn't shared yet.
+            unsafe {
+                (*node).next = head;
+            }
+            match self
+                .head
+                .compare_exchange_weak(head, node, Ordering::Release, Ordering::Relaxed)
+            {
+                Ok(_) => break,
+                Err(current) => head = current,
+            }
         }
     }
 
-    /// Locks the timer queue.
+    /// Drains every timing queued so far, in the order they were queued.
     ///
-    /// Blocks the current thread until the lock can be obtained.
-    pub fn lock(&self) -> Result<MutexGuard<Vec<Timing>>, PoisonError<MutexGuard<Vec<Timing>>>> {
-        self.queue.lock()
+    /// This atomically swaps out the whole stack, so it never blocks and never loses timings
+    /// queued concurrently from another thread (they just land in the next `drain`).
+    pub fn drain(&self) -> Vec<Timing> {
+        let mut head = self.head.swap(ptr::null_mut(), Ordering::Acquire);
+
+        let mut timings = Vec::new();
+        while !head.is_null() {
+            // SAFETY: head was pushed by `queue`, which always allocates via Box::into_raw, and
+            // this is the only place nodes are ever freed, so each node is read exactly once.
+            let node = unsafe { Box::from_raw(head) };
+            head = node.next;
+            timings.push(node.timing);
+        }
+
+        // the stack is pushed front-to-back, so popping it yields timings newest-first; reverse
+        // to restore queue order.
+        timings.reverse();
+        timings
+    }
+
+    /// Drains the queue and folds it into per-name aggregate [`Stats`].
+    ///
+    /// Timings are keyed by [`Timing::name`], so the labels produced by [`t!`]/[`func!`] (which
+    /// already embed the call site) become natural aggregation buckets: a scope that runs 10,000
+    /// times yields one row with `count: 10000` instead of 10,000 individual samples. The
+    /// `BTreeMap` sorts by name, so the report is in deterministic alphabetical order rather than
+    /// queue order.
+    ///
+    /// This drains the same way [`Timer::drain`] does, so use one or the other depending on
+    /// whether you want the summary or the raw samples.
+    pub fn report(&self) -> BTreeMap<String, Stats> {
+        let mut stats: BTreeMap<String, Stats> = BTreeMap::new();
+        for timing in self.drain() {
+            stats
+                .entry(timing.name)
+                .and_modify(|s| s.update(timing.duration))
+                .or_insert_with(|| Stats::new(timing.duration));
+        }
+        stats
     }
 }
 
-// SAFETY: Timer is just a wrapper around a Mutex.
-unsafe impl std::marker::Sync for Timer {}
+impl Drop for Timer {
+    fn drop(&mut self) {
+        // drain any remaining nodes so they aren't leaked.
+        drop(self.drain());
+    }
+}
+
+// Timer's only field is an AtomicPtr<Node>, and AtomicPtr<T> is unconditionally Send + Sync in
+// std regardless of T (loading/storing the pointer doesn't give safe access to what it points
+// to), so Timer already auto-derives both without help. Don't add `unsafe impl Send`/`Sync` here:
+// they'd be vacuous today, but would silently paper over an unsound blanket impl if a future
+// non-atomic field broke that auto-derive instead of failing to compile.
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::sync::Arc;
+    use std::thread;
+
+    fn timing(name: &str) -> Timing {
+        Timing {
+            name: name.to_string(),
+            begin: Instant::now(),
+            duration: Duration::from_millis(1),
+            outcome: Outcome::Completed,
+            id: 0,
+            parent_id: None,
+            depth: 0,
+        }
+    }
+
+    #[test]
+    fn drain_returns_timings_in_queue_order() {
+        let timer = Timer::new();
+        timer.queue(timing("a"));
+        timer.queue(timing("b"));
+        timer.queue(timing("c"));
+
+        let names: Vec<_> = timer.drain().into_iter().map(|t| t.name).collect();
+        assert_eq!(names, vec!["a", "b", "c"]);
+    }
+
+    #[test]
+    fn drain_empties_the_queue() {
+        let timer = Timer::new();
+        timer.queue(timing("a"));
+        assert_eq!(timer.drain().len(), 1);
+        assert_eq!(timer.drain().len(), 0);
+    }
+
+    #[test]
+    fn concurrent_queue_loses_nothing() {
+        const THREADS: usize = 8;
+        const PER_THREAD: usize = 1000;
+
+        let timer = Arc::new(Timer::new());
+        let handles: Vec<_> = (0..THREADS)
+            .map(|t| {
+                let timer = Arc::clone(&timer);
+                thread::spawn(move || {
+                    for i in 0..PER_THREAD {
+                        timer.queue(timing(&format!("{t}-{i}")));
+                    }
+                })
+            })
+            .collect();
+
+        for handle in handles {
+            handle.join().unwrap();
+        }
+
+        assert_eq!(timer.drain().len(), THREADS * PER_THREAD);
+        // a second drain should see nothing left over from the first.
+        assert_eq!(timer.drain().len(), 0);
+    }
+}
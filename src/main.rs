@@ -1,39 +1,30 @@
-use inputs::{tn, Timer, Timing};
+use spyglass::{t, Span, Timer, Timing};
 
-use std::time::{Duration, Instant};
+use std::time::Duration;
 
 fn main() {
     {
-        let _a = MyTimer::new(tn!("maggie"));
-        let _b = MyTimer::new(tn!("milly"));
+        let _a = MyTimer::new(t!("maggie"));
+        let _b = MyTimer::new(t!("milly"));
         x::something();
-        let _c = MyTimer::new(tn!("molly"));
-        let _d = MyTimer::new(tn!("may"));
+        let _c = MyTimer::new(t!("molly"));
+        let _d = MyTimer::new(t!("may"));
     }
 
-    // wait for all the drop threads to finish
-    std::thread::sleep(Duration::from_micros(1));
-
-    match GLOBAL_TIMER.lock() {
-        Ok(queue) => {
-            for timing in queue.iter() {
-                println!(
-                    "{} took {}s ({}ns)",
-                    timing.name,
-                    timing.duration.as_secs(),
-                    timing.duration.as_nanos()
-                );
-            }
-        }
-
-        Err(e) => eprintln!("couldn't check timings: {}", e),
+    for timing in GLOBAL_TIMER.drain() {
+        println!(
+            "{} took {}s ({}ns)",
+            timing.name,
+            timing.duration.as_secs(),
+            timing.duration.as_nanos()
+        );
     }
 }
 
 mod x {
     use super::*;
     pub fn something() {
-        let _x = MyTimer::new(tn!("something"));
+        let _x = MyTimer::new(t!("something"));
         std::thread::sleep(Duration::from_secs(14));
     }
 }
@@ -41,7 +32,7 @@ mod x {
 #[derive(Debug)]
 pub struct MyTimer {
     name: Option<String>,
-    begin: Instant,
+    span: Option<Span>,
 }
 
 impl MyTimer {
@@ -49,23 +40,19 @@ impl MyTimer {
     pub fn new(name: String) -> Self {
         MyTimer {
             name: Some(name),
-            begin: Instant::now(),
+            span: Some(Span::begin()),
         }
     }
 
     fn end(&mut self) -> Timing {
-        Timing {
-            name: self.name.take().unwrap(),
-            begin: self.begin,
-            duration: Instant::now() - self.begin,
-        }
+        self.span.take().unwrap().end(self.name.take().unwrap())
     }
 }
 
 impl Drop for MyTimer {
     fn drop(&mut self) {
         let end = self.end();
-        std::thread::spawn(move || GLOBAL_TIMER.queue(end));
+        GLOBAL_TIMER.queue(end);
     }
 }
 
@@ -0,0 +1,271 @@
+//! Reconstructing the call tree between otherwise-independent [`Timing`]s.
+//!
+//! Each [`MyTimer`]-style guard only knows about itself, so a flat `Vec<Timing>` loses the
+//! parent/child relationship between, say, a `something_expensive` timer and the timers it runs
+//! nested inside. [`Span`] tracks a thread-local stack of currently-open spans so every [`Timing`]
+//! can record its `parent_id` and `depth`, and [`Timer::tree`] turns that back into a tree of
+//! [`SpanNode`]s.
+//!
+//! [`MyTimer`]: crate
+
+use crate::{Outcome, Timer, Timing};
+
+use std::cell::RefCell;
+use std::fmt;
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::time::{Duration, Instant};
+
+static NEXT_SPAN_ID: AtomicU64 = AtomicU64::new(0);
+
+thread_local! {
+    /// IDs of spans currently open on this thread, outermost first.
+    static OPEN_SPANS: RefCell<Vec<u64>> = RefCell::new(Vec::new());
+}
+
+/// Tracks one entry on the thread-local stack of currently-open spans.
+///
+/// Use in place of a raw `Instant::now()` when a scoped timer begins, and call [`Span::end`] with
+/// its name (typically from `Drop`, or once an async span resolves) to get back a fully populated
+/// [`Timing`].
+///
+/// [`Span::begin`] pushes onto the thread-local open-span stack on the calling thread, and relies
+/// on [`Span::end`] (via `Drop`) popping it back off on *that same thread*. That only holds for
+/// spans whose begin and end run on one OS thread, which a synchronous scope guard guarantees
+/// (Rust values don't migrate threads on their own) but a `Future` does not: under a
+/// work-stealing executor, a task
+/// can be polled to completion on a different worker thread than the one that created it, which
+/// would leak the stack entry on the origin thread and corrupt `parent_id`/`depth` for every span
+/// opened there afterwards. Code that can't guarantee same-thread begin/end (see `timed` and
+/// `with_timeout`) should use [`Span::detached`] instead, which records a `parent_id`/`depth`
+/// snapshot without joining the live stack, so there's nothing for a migrated poll to fail to
+/// clean up.
+#[derive(Debug)]
+pub struct Span {
+    id: u64,
+    parent_id: Option<u64>,
+    depth: usize,
+    begin: Instant,
+    /// Whether this span was pushed onto `OPEN_SPANS` and therefore needs Drop to pop it back off.
+    attached: bool,
+}
+
+impl Span {
+    /// Opens a new span, nested under whichever span (if any) is already open on this thread.
+    ///
+    /// Only use this when the same OS thread is guaranteed to both open and close the span (e.g.
+    /// a synchronous scope guard's `new`/`Drop`). For anything that might be polled to completion
+    /// on a different thread than it was created on, use [`Span::detached`] instead.
+    #[must_use]
+    pub fn begin() -> Self {
+        let (id, parent_id, depth) = Self::snapshot();
+        OPEN_SPANS.with(|stack| stack.borrow_mut().push(id));
+
+        Span {
+            id,
+            parent_id,
+            depth,
+            begin: Instant::now(),
+            attached: true,
+        }
+    }
+
+    /// Opens a new span without pushing it onto the thread-local open-span stack.
+    ///
+    /// Its `parent_id`/`depth` are still taken from whatever's open on the calling thread at this
+    /// moment, so it still nests correctly under a synchronous caller. But since it never joins
+    /// the live stack, there's nothing for [`Span::end`]/`Drop` to pop, no matter which thread
+    /// they run on — at the cost of a detached span not becoming the live parent of any
+    /// synchronous spans opened elsewhere while it's in flight.
+    #[must_use]
+    pub fn detached() -> Self {
+        let (id, parent_id, depth) = Self::snapshot();
+
+        Span {
+            id,
+            parent_id,
+            depth,
+            begin: Instant::now(),
+            attached: false,
+        }
+    }
+
+    fn snapshot() -> (u64, Option<u64>, usize) {
+        let id = NEXT_SPAN_ID.fetch_add(1, Ordering::Relaxed);
+        let (parent_id, depth) = OPEN_SPANS.with(|stack| {
+            let stack = stack.borrow();
+            (stack.last().copied(), stack.len())
+        });
+        (id, parent_id, depth)
+    }
+
+    /// Closes the span and produces the finished [`Timing`], with `outcome` set to
+    /// [`Outcome::Completed`]. A span opened with [`Span::begin`] is popped off the thread-local
+    /// stack via `Drop`, so one that's abandoned instead of ended (e.g. a cancelled guard) still
+    /// gets cleaned up, it just never produces a `Timing`.
+    pub fn end(self, name: impl Into<String>) -> Timing {
+        Timing {
+            name: name.into(),
+            begin: self.begin,
+            duration: self.begin.elapsed(),
+            outcome: Outcome::Completed,
+            id: self.id,
+            parent_id: self.parent_id,
+            depth: self.depth,
+        }
+    }
+}
+
+impl Drop for Span {
+    fn drop(&mut self) {
+        if !self.attached {
+            return;
+        }
+
+        OPEN_SPANS.with(|stack| {
+            let mut stack = stack.borrow_mut();
+            // Spans normally close in LIFO order along with the scopes they time, so this is
+            // usually just a pop; fall back to a linear search in case one closed out of order.
+            match stack.last() {
+                Some(&id) if id == self.id => {
+                    stack.pop();
+                }
+                _ => {
+                    if let Some(pos) = stack.iter().rposition(|&id| id == self.id) {
+                        stack.remove(pos);
+                    }
+                }
+            }
+        });
+    }
+}
+
+/// One node of the call tree built by [`Timer::tree`]: a [`Timing`] together with the spans that
+/// opened while it was open.
+#[derive(Debug)]
+pub struct SpanNode {
+    pub timing: Timing,
+    pub children: Vec<SpanNode>,
+}
+
+impl SpanNode {
+    /// This span's duration minus the sum of its direct children's durations.
+    pub fn self_time(&self) -> Duration {
+        let children_total: Duration = self.children.iter().map(|child| child.timing.duration).sum();
+        self.timing.duration.saturating_sub(children_total)
+    }
+
+    /// Writes this node and its descendants as an indented tree, two spaces per depth level, with
+    /// each line showing the span's name, total duration, and self time.
+    pub fn print(&self, f: &mut impl fmt::Write) -> fmt::Result {
+        self.print_indented(f)
+    }
+
+    fn print_indented(&self, f: &mut impl fmt::Write) -> fmt::Result {
+        writeln!(
+            f,
+            "{:indent$}{} - {:?} (self {:?})",
+            "",
+            self.timing.name,
+            self.timing.duration,
+            self.self_time(),
+            indent = self.timing.depth * 2,
+        )?;
+        for child in &self.children {
+            child.print_indented(f)?;
+        }
+        Ok(())
+    }
+}
+
+impl Timer {
+    /// Drains the queue and reassembles the spans' `parent_id`/`depth` into a forest of
+    /// [`SpanNode`]s.
+    ///
+    /// A timing whose `parent_id` doesn't point at another drained timing (including one with no
+    /// parent at all) becomes a root; everything else nests under its parent, in the order it was
+    /// queued.
+    pub fn tree(&self) -> Vec<SpanNode> {
+        let timings = self.drain();
+        let ids: std::collections::HashSet<u64> = timings.iter().map(|timing| timing.id).collect();
+
+        let mut children: std::collections::BTreeMap<u64, Vec<Timing>> = Default::default();
+        let mut roots = Vec::new();
+        for timing in timings {
+            match timing.parent_id {
+                Some(parent_id) if ids.contains(&parent_id) => {
+                    children.entry(parent_id).or_default().push(timing);
+                }
+                _ => roots.push(timing),
+            }
+        }
+
+        fn build(
+            timing: Timing,
+            children: &mut std::collections::BTreeMap<u64, Vec<Timing>>,
+        ) -> SpanNode {
+            let kids = children.remove(&timing.id).unwrap_or_default();
+            SpanNode {
+                children: kids.into_iter().map(|kid| build(kid, children)).collect(),
+                timing,
+            }
+        }
+
+        roots
+            .into_iter()
+            .map(|timing| build(timing, &mut children))
+            .collect()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::thread;
+
+    // borrows the names from the crate's `main.rs` example, but closes maggie before milly opens:
+    // two sibling spans (maggie, milly), with a third (something) nested inside milly's scope.
+    #[test]
+    fn tree_nests_spans_opened_while_a_parent_is_open() {
+        let timer = Timer::new();
+
+        let maggie = Span::begin();
+        thread::sleep(Duration::from_millis(5));
+        timer.queue(maggie.end("maggie"));
+
+        let milly = Span::begin();
+        thread::sleep(Duration::from_millis(5));
+        let something = Span::begin();
+        thread::sleep(Duration::from_millis(5));
+        timer.queue(something.end("something"));
+        timer.queue(milly.end("milly"));
+
+        let roots = timer.tree();
+        assert_eq!(roots.len(), 2);
+
+        let maggie = &roots[0];
+        assert_eq!(maggie.timing.name, "maggie");
+        assert_eq!(maggie.timing.parent_id, None);
+        assert_eq!(maggie.timing.depth, 0);
+        assert!(maggie.children.is_empty());
+
+        let milly = &roots[1];
+        assert_eq!(milly.timing.name, "milly");
+        assert_eq!(milly.timing.parent_id, None);
+        assert_eq!(milly.timing.depth, 0);
+        assert_eq!(milly.children.len(), 1);
+
+        let something = &milly.children[0];
+        assert_eq!(something.timing.name, "something");
+        assert_eq!(something.timing.parent_id, Some(milly.timing.id));
+        assert_eq!(something.timing.depth, 1);
+        assert!(something.children.is_empty());
+
+        // milly's self time excludes the time spent inside something.
+        assert_eq!(
+            milly.self_time(),
+            milly.timing.duration.saturating_sub(something.timing.duration)
+        );
+        // a childless span's self time is just its own duration.
+        assert_eq!(something.self_time(), something.timing.duration);
+    }
+}